@@ -1,5 +1,32 @@
+// The VM's traversal stacks are fixed-capacity arrays sized at compile
+// time (see `Stack`), so traversal itself never grows a heap allocation.
+// The crate still uses `alloc`: `T9Vm` owns its program as a `Vec<u8>`,
+// and the higher-level convenience APIs (`compile`, `suggest`,
+// `match_digits`, word iteration) allocate to build results for the
+// caller.
+#![cfg_attr(not(test), no_std)]
 
-#[derive(Copy, Clone)]
+extern crate alloc;
+
+#[cfg(feature = "trace")]
+extern crate std;
+
+use alloc::collections::BinaryHeap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+#[cfg(feature = "trace")]
+macro_rules! trace {
+    ($($arg:tt)*) => { std::println!($($arg)*) };
+}
+#[cfg(not(feature = "trace"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {};
+}
+
+#[derive(Copy, Clone, Default)]
 pub struct Instr(u8);
 
 // bits - mnemonic - Is word, has children, last child
@@ -20,53 +47,75 @@ pub struct Instr(u8);
 // 111 - ZN -  word,  children,  last
 //     Set WF, ptr++, Push N to word stack, ptr += N, yield word
 
-struct Stack<T>(Vec<T>);
+// Fixed-capacity inline stack: no heap, no reallocation. `N` is the max
+// depth, chosen statically by whoever parameterizes `T9Vm`, so overflow is
+// a real `Err(())` rather than an unbounded grow -- the RAM footprint is
+// known at compile time, which is the point on a microcontroller.
+struct Stack<T: Copy + Default, const N: usize> {
+    buf: [T; N],
+    len: usize,
+}
 
-impl<T> Default for Stack<T> {
+impl<T: Copy + Default, const N: usize> Default for Stack<T, N> {
     fn default() -> Self {
-        Stack(vec![])
+        Stack {
+            buf: [T::default(); N],
+            len: 0,
+        }
     }
 }
 
-impl<T> Stack<T> {
+impl<T: Copy + Default, const N: usize> Stack<T, N> {
     fn new() -> Self {
-        Self(Vec::new())
+        Self::default()
     }
 
     fn push(&mut self, t: T) -> Result<(), ()> {
-        Ok(self.0.push(t))
+        if self.len >= N {
+            return Err(());
+        }
+        self.buf[self.len] = t;
+        self.len += 1;
+        Ok(())
     }
 
     fn pop(&mut self) -> Option<T> {
-        self.0.pop()
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.buf[self.len])
     }
 
     fn all(&self) -> &[T] {
-        self.0.as_slice()
+        &self.buf[..self.len]
     }
 
     fn peek(&self) -> Option<&T> {
-        if self.0.is_empty() {
+        if self.len == 0 {
             None
         } else {
-            self.0.get(self.0.len() - 1)
+            self.buf.get(self.len - 1)
         }
     }
 
     fn push_n<I: IntoIterator<Item = T>>(&mut self, nt: I) -> Result<(), ()> {
-        self.0.extend(nt.into_iter());
+        for t in nt.into_iter() {
+            self.push(t)?;
+        }
         Ok(())
     }
 
     fn drop_n(&mut self, n: usize) -> Result<(), ()> {
-        for _ in 0..n {
-            self.0.pop().ok_or(())?;
+        if n > self.len {
+            return Err(());
         }
+        self.len -= n;
         Ok(())
     }
 
     fn clear(&mut self) {
-        self.0.clear();
+        self.len = 0;
     }
 }
 
@@ -194,20 +243,66 @@ impl Instr {
     }
 }
 
-struct T9Vm {
-    control_stack: Stack<Instr>,
-    word_stack: Stack<u8>,
-    prio_addr_stack: Stack<usize>,
+/// Max control/priority stack depth if not otherwise specified: the
+/// deepest chain of nodes `T9Vm` will track at once (roughly, the longest
+/// word's worth of trie nodes).
+pub const DEFAULT_DEPTH: usize = 32;
+/// Max word stack size if not otherwise specified: the longest word (in
+/// bytes) `T9Vm` will assemble at once.
+pub const DEFAULT_WORD_LEN: usize = 64;
+
+pub struct T9Vm<
+    const CTRL_DEPTH: usize = DEFAULT_DEPTH,
+    const WORD_LEN: usize = DEFAULT_WORD_LEN,
+    const PRIO_DEPTH: usize = DEFAULT_DEPTH,
+> {
+    control_stack: Stack<Instr, CTRL_DEPTH>,
+    word_stack: Stack<u8, WORD_LEN>,
+    prio_addr_stack: Stack<usize, PRIO_DEPTH>,
     program_ctr: usize,
     program: Vec<u8>,
+    // Set by `seek_prefix`: the control stack depth that must not be popped
+    // past, so `next_word` stops once it would unwind out of the subtree.
+    bound_depth: Option<usize>,
+    // Set by `push_node` if a word or control chain ever overflowed one of
+    // the fixed-capacity stacks. `next_word` returning `None` normally means
+    // "iteration complete", but an overflow mid-descent also returns `None`
+    // from that call onward; this flag lets a caller tell the two apart
+    // instead of mistaking truncated output for an exhaustive result.
+    truncated: bool,
 }
 
-impl T9Vm {
+impl<const CTRL_DEPTH: usize, const WORD_LEN: usize, const PRIO_DEPTH: usize>
+    T9Vm<CTRL_DEPTH, WORD_LEN, PRIO_DEPTH>
+{
+    pub fn new(program: Vec<u8>) -> Self {
+        Self {
+            control_stack: Stack::default(),
+            word_stack: Stack::default(),
+            prio_addr_stack: Stack::default(),
+            program_ctr: 0,
+            program,
+            bound_depth: None,
+            truncated: false,
+        }
+    }
+
     fn reset(&mut self) {
         self.control_stack.clear();
         self.word_stack.clear();
         self.prio_addr_stack.clear();
         self.program_ctr = 0;
+        self.bound_depth = None;
+        self.truncated = false;
+    }
+
+    /// True if a word or control chain has ever exceeded `WORD_LEN`,
+    /// `CTRL_DEPTH` or `PRIO_DEPTH` since the last `reset` (i.e. since
+    /// construction or the last `seek_prefix` call, which resets the VM). Once
+    /// set, `next_word` may have stopped early rather than having truly
+    /// exhausted the trie; check this before treating a `None` as "done".
+    pub fn truncated(&self) -> bool {
+        self.truncated
     }
 
     fn pop_cstack(&mut self) -> Instr {
@@ -219,8 +314,306 @@ impl T9Vm {
         val
     }
 
-    fn next_word(&mut self) -> Option<&str> {
-        println!("+=+= NEXT WORD =+=+");
+    // True once the control stack has unwound back to the depth recorded by
+    // `seek_prefix`, i.e. any further pop would leave the sought subtree.
+    fn at_bound(&self) -> bool {
+        match self.bound_depth {
+            Some(bound) => self.control_stack.all().len() <= bound,
+            None => false,
+        }
+    }
+
+    // Reads the instruction at `program_ctr` and its label bytes without
+    // mutating any VM state. Used by `seek_prefix` to decide whether to
+    // descend into, or skip past, a sibling.
+    fn peek_node(&self) -> Option<(Instr, &[u8])> {
+        let instr: Instr = (*self.program.get(self.program_ctr)?).into();
+        let mut ptr = self.program_ctr + 1;
+        if instr.is_word() {
+            ptr += 1;
+        }
+        let label = self.program.get(ptr..ptr + instr.len())?;
+        Some((instr, label))
+    }
+
+    // Advances `program_ctr` past the instruction at the current position
+    // and, if it has children, past its entire child subtree, landing on
+    // its next sibling (or past the end, if it was last).
+    fn skip_node(&mut self) -> Option<()> {
+        self.program_ctr = self.node_end(self.program_ctr)?;
+        Some(())
+    }
+
+    // Pure version of `skip_node`: returns the address just past the node
+    // at `addr` (and its entire child subtree, if any), without touching
+    // `program_ctr`. Used where several branches must be explored without
+    // sharing a single traversal cursor (e.g. `match_digits`).
+    fn node_end(&self, addr: usize) -> Option<usize> {
+        let instr: Instr = (*self.program.get(addr)?).into();
+        let mut ptr = addr + 1;
+        if instr.is_word() {
+            ptr += 1;
+        }
+        ptr += instr.len();
+
+        if instr.has_children() {
+            let mut child = ptr;
+            loop {
+                let child_instr: Instr = (*self.program.get(child)?).into();
+                let next = self.node_end(child)?;
+                if child_instr.is_last() {
+                    ptr = next;
+                    break;
+                }
+                child = next;
+            }
+        }
+
+        Some(ptr)
+    }
+
+    // Pushes the instruction at `program_ctr` onto the control stack (and
+    // its priority/label bytes onto the prio/word stacks), advancing
+    // `program_ctr` past it. This is the shared "push + execute" step used
+    // by both `next_word` and `seek_prefix`.
+    fn push_node(&mut self) -> Option<Instr> {
+        let cur_instr: Instr = (*self.program.get(self.program_ctr)?).into();
+
+        // Push instr onto control stack. A chain deeper than `CTRL_DEPTH` is
+        // a real, reachable condition with fixed-capacity stacks (not a
+        // logic bug), so record it via `truncated` rather than asserting.
+        self.control_stack.push(cur_instr)
+            .map_err(|_| { self.truncated = true; })
+            .ok()?;
+        self.program_ctr += 1;
+
+        // If it's a word, grab the priority byte
+        if cur_instr.is_word() {
+            self.prio_addr_stack.push(self.program_ctr)
+                .map_err(|_| { self.truncated = true; })
+                .ok()?;
+            self.program_ctr += 1;
+        }
+
+        // Push word contents onto word stack
+        for _ in 0..cur_instr.len() {
+            self.word_stack.push(*self.program.get(self.program_ctr)?)
+                .map_err(|_| { self.truncated = true; })
+                .ok()?;
+            self.program_ctr += 1;
+        }
+
+        Some(cur_instr)
+    }
+
+    /// Restricts traversal to the subtree rooted at `prefix`, so that the
+    /// following `next_word` calls enumerate exactly the words beneath it
+    /// (e.g. `"app"` -> `apple`, `applets`, `apples`, `appnote`) and then
+    /// stop. Returns `false` (leaving the VM reset) if `prefix` isn't
+    /// present in the trie.
+    pub fn seek_prefix(&mut self, prefix: &[u8]) -> bool {
+        self.reset();
+
+        if self.seek_prefix_inner(prefix) {
+            true
+        } else {
+            self.reset();
+            false
+        }
+    }
+
+    // The guts of `seek_prefix`, which may leave partially-descended state
+    // on the control/word/prio stacks when it fails partway through; the
+    // caller resets on a `false` result.
+    fn seek_prefix_inner(&mut self, prefix: &[u8]) -> bool {
+        if prefix.is_empty() {
+            // Every node is "under" the empty prefix, so there's nothing
+            // to bound: leave `bound_depth` unset and let `next_word`
+            // enumerate the whole trie, root siblings included.
+            return self.peek_node().is_some();
+        }
+
+        let mut remaining = prefix;
+
+        loop {
+            let (instr, label) = match self.peek_node() {
+                Some(v) => v,
+                None => return false,
+            };
+
+            // Siblings are stored in sorted order, so compare the next
+            // prefix byte(s) against this sibling's label.
+            let cmp_len = remaining.len().min(label.len());
+
+            if label[..cmp_len] == remaining[..cmp_len] {
+                let label_len = label.len();
+
+                if remaining.len() <= label_len {
+                    // Prefix is fully consumed at (or inside) this node. The
+                    // bound is this node's own depth once it's on the
+                    // control stack, whether we push it now or leave that
+                    // to `next_word`: nothing may ever pop below it, since
+                    // that would escape its subtree into its siblings'.
+                    let bound = self.control_stack.all().len() + 1;
+
+                    if instr.is_word() {
+                        // Leave it un-pushed so the ordinary push + execute
+                        // logic below picks it up fresh and yields it (and
+                        // its subtree) exactly as a normal traversal would.
+                    } else if self.push_node().is_none() {
+                        // Nothing to yield for a bare branch node: push it
+                        // now so enumeration starts from its children.
+                        return false;
+                    }
+
+                    self.bound_depth = Some(bound);
+                    return true;
+                }
+
+                // Prefix continues past this node, so it's a definite
+                // ancestor: commit to descending into it.
+                let has_children = instr.has_children();
+                if self.push_node().is_none() {
+                    return false;
+                }
+                remaining = &remaining[label_len..];
+
+                if !has_children {
+                    return false;
+                }
+
+                continue;
+            }
+
+            // Sorted ascending: once a sibling's label already exceeds the
+            // prefix, no later sibling can match either.
+            if label[..cmp_len] > remaining[..cmp_len] {
+                return false;
+            }
+
+            if instr.is_last() {
+                return false;
+            }
+
+            if self.skip_node().is_none() {
+                return false;
+            }
+        }
+    }
+
+    // The priority byte of the word node currently on top of the control
+    // stack (i.e. the one most recently yielded by `next_word`).
+    fn current_priority(&self) -> Option<u8> {
+        let addr = *self.prio_addr_stack.peek()?;
+        self.program.get(addr).copied()
+    }
+
+    /// Enumerates every word under `prefix` and returns the `n`
+    /// highest-priority ones, sorted by descending priority. Memory is
+    /// bounded to `O(n)` regardless of how many words live under the
+    /// prefix: a capacity-`n` min-heap tracks the current top-`n`,
+    /// discarding the lowest-priority candidate whenever a better one
+    /// comes in.
+    pub fn suggest(&mut self, prefix: &[u8], n: usize) -> Vec<(String, u8)> {
+        if n == 0 || !self.seek_prefix(prefix) {
+            return Vec::new();
+        }
+
+        let mut heap: BinaryHeap<Reverse<(u8, String)>> = BinaryHeap::with_capacity(n + 1);
+
+        while let Some(word) = self.next_word() {
+            let word = word.to_string();
+            let prio = self.current_priority().unwrap_or(0);
+            heap.push(Reverse((prio, word)));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        let mut out = Vec::with_capacity(heap.len());
+        while let Some(Reverse(item)) = heap.pop() {
+            out.push(item);
+        }
+        out.reverse();
+        out.into_iter().map(|(prio, word)| (word, prio)).collect()
+    }
+
+    /// Matches a T9 keypad digit sequence (`2`=abc, `3`=def, ... `9`=wxyz)
+    /// against the trie, returning every word whose letters fall under the
+    /// corresponding digit at each position. This is a bounded
+    /// breadth-first walk: a worklist of `(program address, assembled word
+    /// so far, digits consumed)` frontier states advances one trie node at
+    /// a time, branching at each sibling whose label byte is in the active
+    /// digit's letter set and discarding the rest.
+    pub fn match_digits(&mut self, digits: &[u8]) -> Vec<String> {
+        let mut results = Vec::new();
+
+        if digits.is_empty() {
+            return results;
+        }
+
+        let mut worklist = alloc::collections::VecDeque::from(vec![(0usize, Vec::<u8>::new(), 0usize)]);
+
+        while let Some((start, word, digit_index)) = worklist.pop_front() {
+            let mut addr = start;
+
+            while let Some(&b) = self.program.get(addr) {
+                let instr: Instr = b.into();
+
+                let mut ptr = addr + 1;
+                if instr.is_word() {
+                    ptr += 1;
+                }
+                let label = match self.program.get(ptr..ptr + instr.len()) {
+                    Some(l) => l,
+                    None => break,
+                };
+
+                let mut matched = word.clone();
+                let mut di = digit_index;
+                let mut matches = true;
+                for &letter in label {
+                    if di >= digits.len() || !digit_letters(digits[di]).contains(&letter) {
+                        matches = false;
+                        break;
+                    }
+                    matched.push(letter);
+                    di += 1;
+                }
+
+                if matches {
+                    if instr.is_word() && di == digits.len() {
+                        if let Ok(s) = String::from_utf8(matched.clone()) {
+                            results.push(s);
+                        }
+                    }
+                    if instr.has_children() && di < digits.len() {
+                        worklist.push_back((ptr + label.len(), matched, di));
+                    }
+                }
+
+                if instr.is_last() {
+                    break;
+                }
+
+                addr = match self.node_end(addr) {
+                    Some(a) => a,
+                    None => break,
+                };
+            }
+        }
+
+        results
+    }
+
+    pub fn next_word(&mut self) -> Option<&str> {
+        trace!("+=+= NEXT WORD =+=+");
+
+        // A failed `push_node` leaves the stacks half-updated; stop here
+        // rather than unwinding through that state.
+        if self.truncated {
+            return None;
+        }
 
         // if !children:
         //     pop one
@@ -233,6 +626,9 @@ impl T9Vm {
         if let Some(i) = self.control_stack.peek() {
             // if !children:
             if !i.has_children() {
+                if self.at_bound() {
+                    return None;
+                }
 
                 // pop one
                 let val = self.pop_cstack();
@@ -241,9 +637,16 @@ impl T9Vm {
                 if val.is_last() {
                     // while peek.last: pop
                     while self.control_stack.peek()?.is_last() {
+                        if self.at_bound() {
+                            return None;
+                        }
                         self.pop_cstack();
                     }
 
+                    if self.at_bound() {
+                        return None;
+                    }
+
                     // pop one
                     self.pop_cstack();
                 }
@@ -252,29 +655,7 @@ impl T9Vm {
 
         // push + execute
         loop {
-            let cur_instr: Instr = (*self.program.get(self.program_ctr)?).into();
-
-            // Push instr onto control stack
-            self.control_stack.push(cur_instr)
-                .map_err(|_| { debug_assert!(false, "debug-only check failed: Control Stack Overflow"); })
-                .ok()?;
-            self.program_ctr += 1;
-
-            // If it's a word, grab the priority byte
-            if cur_instr.is_word() {
-                self.prio_addr_stack.push(self.program_ctr)
-                    .map_err(|_| { debug_assert!(false, "debug-only check failed: Prio Stack Overflow"); })
-                    .ok()?;
-                self.program_ctr += 1;
-            }
-
-            // Push word contents onto word stack
-            for _ in 0..cur_instr.len() {
-                self.word_stack.push(*self.program.get(self.program_ctr)?)
-                    .map_err(|_| { debug_assert!(false, "debug-only check failed: Word Stack Overflow"); })
-                    .ok()?;
-                self.program_ctr += 1;
-            }
+            let cur_instr = self.push_node()?;
 
             if cur_instr.is_word() {
                 // TODO: at some point I will need to decode keycode to chars,
@@ -282,16 +663,215 @@ impl T9Vm {
                 let word = core::str::from_utf8(self.word_stack.all())
                     .map_err(|_| { debug_assert!(false); })
                     .ok()?;
-                println!(" --> {}", word);
+                trace!(" --> {}", word);
                 break Some(word);
             }
         }
     }
+
+    /// Wraps `next_word` in a proper iterator so callers can use `.take(n)`,
+    /// `.filter(...)`, `.collect()`, etc. instead of hand-rolling a
+    /// `while let Some(w) = vm.next_word()` loop. Yields owned `String`s:
+    /// `next_word` borrows `self` mutably and returns a reference into
+    /// `word_stack`, so a zero-copy `Item` would have to be a lending
+    /// iterator instead.
+    pub fn words(&mut self) -> Words<'_, CTRL_DEPTH, WORD_LEN, PRIO_DEPTH> {
+        Words { vm: self }
+    }
+
+    /// Like `words`, but restricted to the subtree under `prefix` (see
+    /// `seek_prefix`). Returns `None` if the prefix isn't present, leaving
+    /// the VM reset.
+    pub fn words_prefix(&mut self, prefix: &[u8]) -> Option<Words<'_, CTRL_DEPTH, WORD_LEN, PRIO_DEPTH>> {
+        if self.seek_prefix(prefix) {
+            Some(Words { vm: self })
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterator returned by `T9Vm::words`/`words_prefix`.
+pub struct Words<'a, const CTRL_DEPTH: usize = DEFAULT_DEPTH, const WORD_LEN: usize = DEFAULT_WORD_LEN, const PRIO_DEPTH: usize = DEFAULT_DEPTH> {
+    vm: &'a mut T9Vm<CTRL_DEPTH, WORD_LEN, PRIO_DEPTH>,
+}
+
+impl<const CTRL_DEPTH: usize, const WORD_LEN: usize, const PRIO_DEPTH: usize> Iterator
+    for Words<'_, CTRL_DEPTH, WORD_LEN, PRIO_DEPTH>
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.vm.next_word().map(ToString::to_string)
+    }
+}
+
+// T9 keypad letter groups: 2=abc, 3=def, ... 9=wxyz. Digits `0` and `1`
+// carry no letters.
+fn digit_letters(digit: u8) -> &'static [u8] {
+    match digit {
+        b'2' => b"abc",
+        b'3' => b"def",
+        b'4' => b"ghi",
+        b'5' => b"jkl",
+        b'6' => b"mno",
+        b'7' => b"pqrs",
+        b'8' => b"tuv",
+        b'9' => b"wxyz",
+        _ => b"",
+    }
+}
+
+/// Compiles a word list into the `Instr` byte stream `T9Vm` expects (the
+/// inverse of `next_word`). Words sharing a prefix become intermediate
+/// non-word nodes; each full word becomes a word node carrying its
+/// priority byte immediately after the instruction byte, before the label
+/// bytes. A label longer than 31 bytes (the 5-bit `len` field's max) is
+/// split into a chain of single-child non-word nodes of up to 31 bytes
+/// each, terminated by the real node.
+///
+/// `compile` itself has no capacity limit, but the `T9Vm` that drives the
+/// result does: the caller must pick `WORD_LEN`/`CTRL_DEPTH` const
+/// generics at least as large as the longest word and deepest node chain
+/// being compiled (`DEFAULT_WORD_LEN`/`DEFAULT_DEPTH` if unspecified), or
+/// traversal will stop short once a stack fills up.
+// T9 keypad letter groups: 2=abc, 3=def, ... 9=wxyz. Digits `0` and `1`
+// carry no letters.
+fn digit_letters(digit: u8) -> &'static [u8] {
+    match digit {
+        b'2' => b"abc",
+        b'3' => b"def",
+        b'4' => b"ghi",
+        b'5' => b"jkl",
+        b'6' => b"mno",
+        b'7' => b"pqrs",
+        b'8' => b"tuv",
+        b'9' => b"wxyz",
+        _ => b"",
+    }
+}
+
+pub fn compile(words: &[(&str, u8)]) -> Vec<u8> {
+    struct BuildNode {
+        label: Vec<u8>,
+        prio: Option<u8>,
+        children: Vec<BuildNode>,
+    }
+
+    fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+        a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+    }
+
+    fn insert(children: &mut Vec<BuildNode>, word: &[u8], prio: u8) {
+        if let Some(pos) = children.iter().position(|c| c.label[0] == word[0]) {
+            let common = common_prefix_len(&children[pos].label, word);
+            if common < children[pos].label.len() {
+                // Split the existing child: the shared `common` bytes stay
+                // on it, the remainder becomes its sole new child.
+                let child = &mut children[pos];
+                let tail_label = child.label.split_off(common);
+                let tail = BuildNode {
+                    label: tail_label,
+                    prio: child.prio.take(),
+                    children: core::mem::take(&mut child.children),
+                };
+                child.children = vec![tail];
+            }
+
+            let rest = &word[common..];
+            if rest.is_empty() {
+                children[pos].prio = Some(prio);
+            } else {
+                insert(&mut children[pos].children, rest, prio);
+            }
+        } else {
+            children.push(BuildNode {
+                label: word.to_vec(),
+                prio: Some(prio),
+                children: Vec::new(),
+            });
+        }
+    }
+
+    fn sort_children(children: &mut [BuildNode]) {
+        children.sort_by(|a, b| a.label.cmp(&b.label));
+        for child in children.iter_mut() {
+            sort_children(&mut child.children);
+        }
+    }
+
+    fn emit(node: &BuildNode, is_last: bool, out: &mut Vec<u8>) {
+        let chunks: Vec<&[u8]> = node.label.chunks(31).collect();
+        let last_idx = chunks.len() - 1;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            // The first chunk inherits the real node's sibling position;
+            // every chunk after it is the sole child of the one before,
+            // so it's always "last" within its own one-element list.
+            let chunk_is_last = if i == 0 { is_last } else { true };
+
+            if i == last_idx {
+                let is_word = node.prio.is_some();
+                let has_children = !node.children.is_empty();
+                let instr = match (is_word, has_children, chunk_is_last) {
+                    (false, false, false) => Instruction::S_NotWordNoChildrenNotLast,
+                    (false, false, true) => Instruction::T_NotWordNoChildrenIsLast,
+                    (false, true, false) => Instruction::U_NotWordHasChildrenNotLast,
+                    (false, true, true) => Instruction::V_NotWordHasChildrenIsLast,
+                    (true, false, false) => Instruction::W_IsWordNoChildrenNotLast,
+                    (true, false, true) => Instruction::X_IsWordNoChildrenIsLast,
+                    (true, true, false) => Instruction::Y_IsWordHasChildrenNotLast,
+                    (true, true, true) => Instruction::Z_IsWordHasChildrenIsLast,
+                };
+
+                out.push(Instr::from_len_instr(chunk.len() as u8, instr).0);
+                if let Some(prio) = node.prio {
+                    out.push(prio);
+                }
+                out.extend_from_slice(chunk);
+            } else {
+                let instr = if chunk_is_last {
+                    Instruction::V_NotWordHasChildrenIsLast
+                } else {
+                    Instruction::U_NotWordHasChildrenNotLast
+                };
+
+                out.push(Instr::from_len_instr(chunk.len() as u8, instr).0);
+                out.extend_from_slice(chunk);
+            }
+        }
+
+        for (i, child) in node.children.iter().enumerate() {
+            emit(child, i + 1 == node.children.len(), out);
+        }
+    }
+
+    let mut sorted_words: Vec<(&str, u8)> = words.to_vec();
+    sorted_words.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut root: Vec<BuildNode> = Vec::new();
+    for (word, prio) in sorted_words {
+        // The trie has no representation for a zero-length label at the
+        // root, and an empty word has no keypad digits to begin with, so
+        // there's nothing meaningful to insert: skip it rather than panic
+        // on the unguarded `word[0]` lookups in `insert`.
+        if word.is_empty() {
+            continue;
+        }
+        insert(&mut root, word.as_bytes(), prio);
+    }
+    sort_children(&mut root);
+
+    let mut out = Vec::new();
+    for (i, node) in root.iter().enumerate() {
+        emit(node, i + 1 == root.len(), &mut out);
+    }
+    out
 }
 
 #[cfg(test)]
 pub mod test {
-    use crate::{Instruction, Instr, T9Vm, Stack};
+    use crate::{compile, Instruction, Instr, T9Vm};
 
     const fn u(len: u8) -> u8 {
         Instr::from_len_instr(len, Instruction::U_NotWordHasChildrenNotLast).0
@@ -387,13 +967,7 @@ pub mod test {
             String::from("bite"),
         ];
 
-        let mut vm = T9Vm {
-            control_stack: Stack::<_>::default(),
-            word_stack: Stack::<_>::default(),
-            prio_addr_stack: Stack::<_>::default(),
-            program_ctr: 0,
-            program,
-        };
+        let mut vm: T9Vm = T9Vm::new(program);
 
         let mut outs = Vec::new();
 
@@ -417,64 +991,248 @@ pub mod test {
         // proposed[..target.len()] == target
     }
 
-    // #[test]
-    // fn submatch() {
-    //     // 000 - S - !word, !children, !last
-    //     // 001 - T - !word, !children,  last
-    //     // 010 - U - !word,  children, !last
-    //     // 011 - V - !word,  children,  last
-    //     // 100 - W -  word, !children, !last
-    //     // 101 - X -  word, !children,  last
-    //     // 110 - Y -  word,  children, !last
-    //     // 111 - Z -  word,  children,  last
-    //     let program = DEMO.iter().copied().collect::<Vec<u8>>();
-    //     let expected_a = [
-    //         String::from("a"),
-    //         String::from("aaron"),
-    //         String::from("aarons"),
-    //         String::from("ab"),
-    //         String::from("able"),
-    //         String::from("apple"),
-    //         String::from("applets"),
-    //         String::from("apples"),
-    //         String::from("appnote"),
-    //         String::from("as"),
-    //     ];
-    //     let expected_ap = [
-    //         String::from("apple"),
-    //         String::from("applets"),
-    //         String::from("apples"),
-    //         String::from("appnote"),
-    //     ];
-    //     let expected_app = [
-    //         String::from("apple"),
-    //         String::from("applets"),
-    //         String::from("apples"),
-    //     ];
-    //     let expected_appl = [
-    //         String::from("apple"),
-    //         String::from("applets"),
-    //         String::from("apples"),
-    //     ];
-    //     let expected_applz: [String; 0] = [];
-
-    //     let mut vm = T9Vm {
-    //         control_stack: Stack::<_>::default(),
-    //         word_stack: Stack::<_>::default(),
-    //         prio_addr_stack: Stack::<_>::default(),
-    //         program_ctr: 0,
-    //         program,
-    //     };
-
-    //     let mut outs = Vec::new();
-
-    //     while let Some(w) = vm.next_word() {
-    //         outs.push(w.to_string());
-    //     }
-
-    //     assert_eq!(
-    //         expected.as_slice(),
-    //         outs.as_slice(),
-    //     );
-    // }
+    #[test]
+    fn suggest_top_n() {
+        // Shared prefix "a" with three leaf words of differing priority.
+        let program: Vec<u8> = vec![
+            v(1), b'a',
+            w(1), 5, b'n',
+            w(1), 9, b't',
+            x(1), 1, b'x',
+        ];
+
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        assert_eq!(
+            vm.suggest(b"a", 2),
+            vec![(String::from("at"), 9), (String::from("an"), 5)],
+        );
+
+        assert_eq!(
+            vm.suggest(b"a", 5),
+            vec![
+                (String::from("at"), 9),
+                (String::from("an"), 5),
+                (String::from("ax"), 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn suggest_empty_prefix() {
+        // Three unrelated top-level words: the empty prefix is the "top N
+        // overall" call, so it must consider all of them, not just the
+        // first root sibling.
+        let program: Vec<u8> = vec![
+            w(1), 5, b'a',
+            w(1), 9, b'b',
+            x(1), 1, b'z',
+        ];
+
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        assert_eq!(
+            vm.suggest(b"", 2),
+            vec![(String::from("b"), 9), (String::from("a"), 5)],
+        );
+
+        assert_eq!(
+            vm.suggest(b"", 5),
+            vec![
+                (String::from("b"), 9),
+                (String::from("a"), 5),
+                (String::from("z"), 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn submatch() {
+        let program = DEMO.iter().copied().collect::<Vec<u8>>();
+
+        let expected_ap = [
+            String::from("apple"),
+            String::from("applets"),
+            String::from("apples"),
+            String::from("appnote"),
+            String::from("appnote_a"),
+            String::from("appnote_ab"),
+            String::from("appnote_abc"),
+            String::from("appnote_abcd"),
+            String::from("appnote_abe"),
+            String::from("appnote_abef"),
+        ];
+        let expected_appl = [
+            String::from("apple"),
+            String::from("applets"),
+            String::from("apples"),
+        ];
+
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        assert!(vm.seek_prefix(b"ap"));
+        let mut outs = Vec::new();
+        while let Some(w) = vm.next_word() {
+            outs.push(w.to_string());
+        }
+        assert_eq!(expected_ap.as_slice(), outs.as_slice());
+
+        assert!(vm.seek_prefix(b"appl"));
+        let mut outs = Vec::new();
+        while let Some(w) = vm.next_word() {
+            outs.push(w.to_string());
+        }
+        assert_eq!(expected_appl.as_slice(), outs.as_slice());
+
+        assert!(!vm.seek_prefix(b"applz"));
+
+        // The empty prefix matches everything: it must behave like
+        // unrestricted traversal, not stop at the first root sibling.
+        let expected_all = [
+            String::from("a"),
+            String::from("aaron"),
+            String::from("aarons"),
+            String::from("ab"),
+            String::from("able"),
+            String::from("apple"),
+            String::from("applets"),
+            String::from("apples"),
+            String::from("appnote"),
+            String::from("appnote_a"),
+            String::from("appnote_ab"),
+            String::from("appnote_abc"),
+            String::from("appnote_abcd"),
+            String::from("appnote_abe"),
+            String::from("appnote_abef"),
+            String::from("as"),
+            String::from("bite"),
+        ];
+        assert!(vm.seek_prefix(b""));
+        let mut outs = Vec::new();
+        while let Some(w) = vm.next_word() {
+            outs.push(w.to_string());
+        }
+        assert_eq!(expected_all.as_slice(), outs.as_slice());
+    }
+
+    #[test]
+    fn compile_round_trip() {
+        let words = [
+            ("banana", 3u8),
+            ("band", 1),
+            ("ban", 7),
+            ("apple", 2),
+            ("app", 9),
+        ];
+
+        let program = compile(&words);
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        let mut expected: Vec<String> = words.iter().map(|(w, _)| w.to_string()).collect();
+        expected.sort();
+
+        let mut outs = Vec::new();
+        while let Some(w) = vm.next_word() {
+            outs.push(w.to_string());
+        }
+
+        assert_eq!(expected, outs);
+    }
+
+    #[test]
+    fn compile_ignores_empty_words() {
+        // An empty word has no digits and no place in the trie; compile()
+        // should skip it rather than panic on the unguarded label lookups
+        // in `insert`.
+        let words = [("", 1u8), ("a", 2)];
+
+        let program = compile(&words);
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        let mut outs = Vec::new();
+        while let Some(w) = vm.next_word() {
+            outs.push(w.to_string());
+        }
+
+        assert_eq!(outs, vec![String::from("a")]);
+    }
+
+    #[test]
+    fn next_word_reports_truncation_on_stack_overflow() {
+        // A word stack too small to hold the word's label: `next_word`
+        // still has to return `None` (there's nowhere to put the bytes),
+        // but `truncated()` must tell that apart from an exhausted trie.
+        let program = compile(&[("hello", 1)]);
+        let mut vm: T9Vm<32, 4, 32> = T9Vm::new(program);
+
+        assert!(!vm.truncated());
+        assert_eq!(vm.next_word(), None);
+        assert!(vm.truncated());
+    }
+
+    #[test]
+    fn compile_splits_long_labels() {
+        let long = "a".repeat(40);
+        let words = [(long.as_str(), 4u8)];
+
+        let program = compile(&words);
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        let mut outs = Vec::new();
+        while let Some(w) = vm.next_word() {
+            outs.push(w.to_string());
+        }
+
+        assert_eq!(outs, vec![long]);
+    }
+
+    #[test]
+    fn match_digits_keypad() {
+        // "am" and "an" both key as 2-6 ('a'=abc, 'm'/'n'=mno); "ab" keys
+        // as 2-2, and "do" keys as 3-6, so they shouldn't show up.
+        let words = [("am", 1u8), ("an", 2), ("ab", 3), ("do", 4)];
+        let program = compile(&words);
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        let mut got = vm.match_digits(b"26");
+        got.sort();
+        assert_eq!(got, vec![String::from("am"), String::from("an")]);
+
+        let got_do = vm.match_digits(b"36");
+        assert_eq!(got_do, vec![String::from("do")]);
+    }
+
+    #[test]
+    fn words_iterator() {
+        let program = DEMO.iter().copied().collect::<Vec<u8>>();
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        let outs: Vec<String> = vm.words().take(3).collect();
+        assert_eq!(
+            outs,
+            vec![
+                String::from("a"),
+                String::from("aaron"),
+                String::from("aarons"),
+            ],
+        );
+    }
+
+    #[test]
+    fn words_prefix_iterator() {
+        let program = DEMO.iter().copied().collect::<Vec<u8>>();
+        let mut vm: T9Vm = T9Vm::new(program);
+
+        let outs: Vec<String> = vm.words_prefix(b"appl").unwrap().collect();
+        assert_eq!(
+            outs,
+            vec![
+                String::from("apple"),
+                String::from("applets"),
+                String::from("apples"),
+            ],
+        );
+
+        assert!(vm.words_prefix(b"applz").is_none());
+    }
 }